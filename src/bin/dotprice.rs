@@ -1,9 +1,13 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use clap::Parser;
-use dotgain::{price::PriceClient, time::TryFromHuman};
+use dotgain::{
+    price::{default_cache_dir, PriceBasis, PriceClient, SourceKind},
+    time::TryFromHuman,
+};
+use std::path::PathBuf;
 
-/// Lookup historic coin price using Binance Public API.
+/// Lookup historic coin price using Binance or Coinbase Public API.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
@@ -11,6 +15,25 @@ struct Args {
     #[arg(short, long, default_value_t = String::from("DOTEUR"))]
     convert: String,
 
+    /// Price source to use. If not set, Binance is tried first, falling
+    /// back to Coinbase on a gap or failure.
+    #[arg(long, value_enum)]
+    source: Option<SourceKind>,
+
+    /// How the reference price is derived from the OHLCV data at the
+    /// selected minute. Defaults to the minute's close price.
+    #[arg(long, value_enum)]
+    price_basis: Option<PriceBasis>,
+
+    /// Directory for the on-disk price cache. Defaults to a `dotgain`
+    /// subdirectory of the platform cache directory.
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// Disable the on-disk price cache entirely.
+    #[arg(long)]
+    no_cache: bool,
+
     /// Date & time in UTC. Example: '2023-02-21 17:53:28'.
     /// Selected minute close price will be returned.
     /// If only date is provided, time 00:00 is assumed.
@@ -19,9 +42,21 @@ struct Args {
 
 fn main() -> Result<()> {
     let args = Args::parse();
-    let mut client = PriceClient::default();
+    let mut client = match args.source {
+        Some(source) => PriceClient::with_source(source),
+        None => PriceClient::default(),
+    };
+    let cache_dir = if args.no_cache {
+        None
+    } else {
+        args.cache_dir.or_else(default_cache_dir)
+    };
+    if let Some(cache_dir) = cache_dir {
+        client = client.with_cache(cache_dir);
+    }
+    let basis = args.price_basis.unwrap_or(PriceBasis::Close);
     let datetime = DateTime::<Utc>::try_from_human(&args.date)?;
-    let price = client.price(&args.convert, datetime)?;
+    let price = client.price(&args.convert, datetime, basis)?;
 
     println!("{price}");
 
@@ -1,16 +1,19 @@
 use anyhow::{anyhow, Context, Result};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono_tz::Tz;
 use clap::Parser;
 use csv::Reader;
 use dotgain::{
-    price::PriceClient,
-    time::{IntoHuman, TryFromHuman},
+    price::{default_cache_dir, minute_timestamp_ms, PriceBasis, PriceClient, SourceKind},
+    time::{localize_to_utc, IntoHuman, TryFromHuman},
 };
 use rust_decimal::Decimal;
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fs::File,
     io::{self, Write},
     path::{Path, PathBuf},
+    time::{Duration as StdDuration, Instant},
 };
 
 const DATE_COLUMN: &str = "Date";
@@ -28,6 +31,37 @@ struct Args {
     #[arg(short, long, default_value_t = String::from("DOTEUR"))]
     convert: String,
 
+    /// Price source to use. If not set, Binance is tried first, falling
+    /// back to Coinbase on a gap or failure.
+    #[arg(long, value_enum)]
+    source: Option<SourceKind>,
+
+    /// How the reference price is derived from the OHLCV data at the reward
+    /// minute. Defaults to the minute's close price.
+    #[arg(long, value_enum)]
+    price_basis: Option<PriceBasis>,
+
+    /// Directory for the on-disk price cache. Defaults to a `dotgain`
+    /// subdirectory of the platform cache directory.
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// Disable the on-disk price cache entirely.
+    #[arg(long)]
+    no_cache: bool,
+
+    /// IANA timezone the reward dates are attributed in, e.g. `America/New_York`.
+    /// Input dates are read as wall-clock times in this timezone and written to
+    /// the report as such. `--begin` and `--end` are not localized: they are
+    /// parsed as UTC and compared against each reward's UTC time.
+    #[arg(long, default_value = "UTC")]
+    timezone: Tz,
+
+    /// Write one row per reward with the raw close price, the chosen basis
+    /// price, fetch latency and a source/quality flag to this CSV file.
+    #[arg(long)]
+    diagnostics_csv: Option<PathBuf>,
+
     /// Start date & time.
     #[arg(short, long, value_parser = DateTime::<Utc>::try_from_human)]
     begin: Option<DateTime<Utc>>,
@@ -46,14 +80,17 @@ struct Args {
 
 struct InputEntry {
     datetime: DateTime<Utc>,
+    local_datetime: NaiveDateTime,
     value: Decimal,
 }
 
 struct OutputEntry {
     datetime: DateTime<Utc>,
+    local_datetime: NaiveDateTime,
     value: Decimal,
     conversion: Decimal,
     fiat_income: Decimal,
+    diagnostics: RowDiagnostics,
 }
 
 struct TotalsEntry {
@@ -62,22 +99,96 @@ struct TotalsEntry {
     total_fiat_income: Decimal,
 }
 
+/// Per-row fetch diagnostics, used for the stderr summary and the optional
+/// `--diagnostics-csv` output.
+struct RowDiagnostics {
+    /// The minute's raw close price, regardless of `--price-basis`. Only
+    /// looked up when `--diagnostics-csv` is set; otherwise it holds the
+    /// basis price.
+    raw_close: f64,
+    /// `"cache"`, the answering source's name, or `"prefetch"` for rows
+    /// served out of the batched range fetch in [`process`].
+    quality: &'static str,
+    /// Time spent fetching this row's basis price. Zero for a prefetched or
+    /// cached row.
+    latency: StdDuration,
+    /// Set when the row's price had to be approximated rather than read
+    /// exactly, e.g. a reward minute where the source returned a timestamp
+    /// mismatch or an empty kline. `None` for prefetched or cached rows,
+    /// which don't carry per-row anomaly information.
+    anomaly: Option<String>,
+}
+
+/// Aggregate counters for the stderr summary printed at the end of a run.
+#[derive(Default)]
+struct RunDiagnostics {
+    rows: usize,
+    prefetched: usize,
+    cache_hits: usize,
+    network_fetches: usize,
+    anomalies: usize,
+    elapsed: StdDuration,
+}
+
+impl RunDiagnostics {
+    fn prices_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.rows as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    fn print_summary(&self) {
+        eprintln!("\nFetch diagnostics:");
+        eprintln!("  rows:             {}", self.rows);
+        eprintln!(
+            "  elapsed:          {:.2}s ({:.1} prices/s)",
+            self.elapsed.as_secs_f64(),
+            self.prices_per_second()
+        );
+        eprintln!("  prefetched:       {}", self.prefetched);
+        eprintln!("  cache hits:       {}", self.cache_hits);
+        eprintln!("  network fetches:  {}", self.network_fetches);
+        eprintln!("  anomalies:        {}", self.anomalies);
+    }
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    let input = read_input(&args.input)?;
+    let input = read_input(&args.input, args.timezone)?;
     let selected = filter_range(input, args.begin, args.end);
-    let report = process(selected, &args.convert)?;
+    let basis = args.price_basis.unwrap_or(PriceBasis::Close);
+    let cache_dir = if args.no_cache {
+        None
+    } else {
+        args.cache_dir.or_else(default_cache_dir)
+    };
+    let (report, diagnostics) = process(
+        selected,
+        &args.convert,
+        args.source,
+        basis,
+        cache_dir,
+        args.diagnostics_csv.is_some(),
+    )?;
     let totals = calculate_totals(&report);
 
+    if let Some(diagnostics_csv) = &args.diagnostics_csv {
+        write_diagnostics_csv(&report, diagnostics_csv)?;
+    }
+
     write_output(report, totals, &args.convert, &args.output)?;
 
     println!("\nDone");
+    diagnostics.print_summary();
 
     Ok(())
 }
 
-fn read_input(path: &Path) -> Result<Vec<InputEntry>> {
+fn read_input(path: &Path, timezone: Tz) -> Result<Vec<InputEntry>> {
     let mut reader = Reader::from_path(path)?;
     let headers = reader.headers()?;
 
@@ -93,18 +204,25 @@ fn read_input(path: &Path) -> Result<Vec<InputEntry>> {
 
     let mut entries = Vec::new();
 
-    for record in reader.records() {
+    for (row, record) in reader.records().enumerate() {
         let record = record?;
         if record.len() < min_columns {
             return Err(anyhow!("not enough columns in a raw"));
         }
 
-        let datetime = DateTime::<Utc>::try_from_human(&record[date_column])?;
+        let local_datetime = NaiveDateTime::try_from_human(&record[date_column])
+            .with_context(|| format!("invalid date in row {}", row + 1))?;
+        let datetime = localize_to_utc(local_datetime, timezone)
+            .with_context(|| format!("cannot localize date in row {}", row + 1))?;
         let value_str = &record[value_column];
         let value = Decimal::from_str_exact(value_str)
             .with_context(|| format!("cannot convert {value_str} to number"))?;
 
-        entries.push(InputEntry { datetime, value });
+        entries.push(InputEntry {
+            datetime,
+            local_datetime,
+            value,
+        });
     }
 
     Ok(entries)
@@ -133,28 +251,122 @@ fn filter_range(
         .collect()
 }
 
-fn process(input: Vec<InputEntry>, symbol: &str) -> Result<Vec<OutputEntry>> {
-    let mut client = PriceClient::default();
+fn process(
+    input: Vec<InputEntry>,
+    symbol: &str,
+    source: Option<SourceKind>,
+    basis: PriceBasis,
+    cache_dir: Option<PathBuf>,
+    want_raw_close: bool,
+) -> Result<(Vec<OutputEntry>, RunDiagnostics)> {
+    let started = Instant::now();
+
+    let mut client = match source {
+        Some(source) => PriceClient::with_source(source),
+        None => PriceClient::default(),
+    };
+    if let Some(cache_dir) = cache_dir {
+        client = client.with_cache(cache_dir);
+    }
     let mut output = Vec::new();
+    let mut diagnostics = RunDiagnostics::default();
 
     let total_lines = input.len();
 
+    // The specific reward-row minutes we'll actually look up below, as
+    // opposed to every minute in `[min, max]`. A cache hit only needs to
+    // cover these, since sources legitimately leave gaps elsewhere.
+    let needed_minutes: BTreeSet<i64> = input
+        .iter()
+        .map(|entry| minute_timestamp_ms(entry.datetime))
+        .collect();
+
+    // Prefetch the whole covering range in batched calls instead of issuing
+    // one request per reward row, which easily trips exchange rate limits
+    // on a year's worth of daily rewards.
+    let prefetched = match min_max_datetime(&input) {
+        Some((min, max)) => client
+            .prices_in_range(symbol, min, max, basis, &needed_minutes)
+            .with_context(|| format!("failed to prefetch prices between {min} and {max}"))?,
+        None => BTreeMap::new(),
+    };
+    diagnostics.prefetched = prefetched.len();
+
+    // When diagnostics need the raw close alongside a non-close basis,
+    // prefetch it the same way as the chosen basis instead of issuing one
+    // `PriceBasis::Close` request per row.
+    let prefetched_close = if want_raw_close && !matches!(basis, PriceBasis::Close) {
+        match min_max_datetime(&input) {
+            Some((min, max)) => client
+                .prices_in_range(symbol, min, max, PriceBasis::Close, &needed_minutes)
+                .with_context(|| format!("failed to prefetch close prices between {min} and {max}"))?,
+            None => BTreeMap::new(),
+        }
+    } else {
+        BTreeMap::new()
+    };
+
     for (i, entry) in input.into_iter().enumerate() {
         print_progress(i + 1, total_lines);
 
-        let conversion = client
-            .price(symbol, entry.datetime)
-            .with_context(|| format!("failed to fetch price for {}", entry.datetime))?;
+        let (conversion, row_quality, row_latency, row_anomaly) =
+            match prefetched.get(&minute_timestamp_ms(entry.datetime)) {
+                Some(price) => (*price, "prefetch", StdDuration::ZERO, None),
+                None => {
+                    let lookup = client
+                        .price_with_diagnostics(symbol, entry.datetime, basis)
+                        .with_context(|| format!("failed to fetch price for {}", entry.datetime))?;
+                    if lookup.is_cache_hit() {
+                        diagnostics.cache_hits += 1;
+                    } else {
+                        diagnostics.network_fetches += 1;
+                    }
+                    if lookup.anomaly.is_some() {
+                        diagnostics.anomalies += 1;
+                    }
+                    (lookup.price, lookup.source, lookup.latency, lookup.anomaly)
+                }
+            };
+
+        let raw_close = if !want_raw_close {
+            conversion
+        } else if matches!(basis, PriceBasis::Close) {
+            conversion
+        } else if let Some(price) = prefetched_close.get(&minute_timestamp_ms(entry.datetime)) {
+            *price
+        } else {
+            client
+                .price(symbol, entry.datetime, PriceBasis::Close)
+                .with_context(|| format!("failed to fetch close price for {}", entry.datetime))?
+        };
 
         output.push(OutputEntry {
             datetime: entry.datetime,
+            local_datetime: entry.local_datetime,
             value: entry.value,
             conversion,
             fiat_income: entry.value * conversion,
+            diagnostics: RowDiagnostics {
+                raw_close,
+                quality: row_quality,
+                latency: row_latency,
+                anomaly: row_anomaly,
+            },
         });
     }
 
-    Ok(output)
+    diagnostics.rows = total_lines;
+    diagnostics.elapsed = started.elapsed();
+
+    Ok((output, diagnostics))
+}
+
+/// Earliest and latest datetime among `input`, used to size the price
+/// prefetch range.
+fn min_max_datetime(input: &[InputEntry]) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let min = input.iter().map(|entry| entry.datetime).min()?;
+    let max = input.iter().map(|entry| entry.datetime).max()?;
+    Some((min, max))
 }
 
 fn print_progress(current: usize, total: usize) {
@@ -182,6 +394,31 @@ fn calculate_totals(report: &[OutputEntry]) -> TotalsEntry {
     }
 }
 
+fn write_diagnostics_csv(report: &[OutputEntry], path: &Path) -> Result<()> {
+    let mut w = File::create(path)
+        .with_context(|| format!("cannot create diagnostics CSV {}", path.display()))?;
+
+    writeln!(
+        &mut w,
+        "{DATE_COLUMN},raw_close,basis_price,latency_ms,quality,anomaly"
+    )?;
+
+    for entry in report {
+        writeln!(
+            &mut w,
+            "{},{},{},{},{},{}",
+            entry.local_datetime.into_human(),
+            entry.diagnostics.raw_close,
+            entry.conversion.normalize(),
+            entry.diagnostics.latency.as_millis(),
+            entry.diagnostics.quality,
+            entry.diagnostics.anomaly.as_deref().unwrap_or("")
+        )?;
+    }
+
+    Ok(())
+}
+
 fn write_output(
     report: Vec<OutputEntry>,
     totals: TotalsEntry,
@@ -201,7 +438,7 @@ fn write_output(
         writeln!(
             &mut w,
             "{},{},{},{}",
-            entry.datetime.naive_utc().into_human(),
+            entry.local_datetime.into_human(),
             entry.value.normalize(),
             entry.conversion.normalize(),
             entry.fiat_income.normalize()
@@ -1,20 +1,439 @@
+mod cache;
+
+pub use cache::default_cache_dir;
+
 use anyhow::{anyhow, Context, Result};
-use chrono::{DateTime, NaiveDateTime, Utc};
+use cache::PriceCache;
+use chrono::{DateTime, Duration, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use clap::ValueEnum;
 use reqwest::{
     blocking::Client,
     header::{HeaderMap, HeaderValue},
     StatusCode,
 };
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::PathBuf,
+    thread,
+    time::{Duration as StdDuration, Instant},
+};
 
-const BASE_URL: &str = "https://api.binance.com";
+/// The on-disk price cache, reported as a pseudo-source name in diagnostics.
+const CACHE_SOURCE_NAME: &str = "cache";
+
+const BINANCE_BASE_URL: &str = "https://api.binance.com";
 const KLINE_FIELDS_NUM: usize = 12;
+const KLINE_PAGE_LIMIT: usize = 1000;
+
+const COINBASE_BASE_URL: &str = "https://api.exchange.coinbase.com";
+const CANDLE_FIELDS_NUM: usize = 6;
+/// Coinbase's historic candle endpoint caps a single response at 300 entries.
+const CANDLE_PAGE_LIMIT: usize = 300;
+
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+const DEFAULT_RETRY_AFTER_SECS: u64 = 1;
+
+/// How many minutes around a requested minute to widen a single-minute
+/// lookup to, when the exchange returns no kline/candle or a timestamp
+/// mismatch for the exact minute. Widened lookups are flagged as anomalies.
+const APPROX_WINDOW_MINUTES: i64 = 5;
+
+/// A single price lookup result, flagged when the source couldn't answer the
+/// requested minute exactly and had to approximate from a nearby one.
+pub struct PriceSample {
+    pub price: f64,
+    /// Set when `price` was approximated from a nearby minute rather than
+    /// read exactly, e.g. because the exchange returned an empty kline or a
+    /// timestamp mismatch for the requested minute.
+    pub anomaly: Option<String>,
+}
+
+/// A source that can answer historic price queries for a trading pair.
+///
+/// Implementations are free to be backed by any exchange API; [`PriceClient`]
+/// chains them together so that a gap or outage in one source doesn't abort
+/// the whole report.
+pub trait PriceSource {
+    /// Fetch the price of `symbol` at `datetime`, derived according to `basis`.
+    fn price(&mut self, symbol: &str, datetime: DateTime<Utc>, basis: PriceBasis) -> Result<PriceSample>;
+
+    /// Fetch prices for every minute in `[start, end]`, indexed by
+    /// minute-truncated timestamp in milliseconds.
+    ///
+    /// The default implementation simply calls [`PriceSource::price`] once
+    /// per minute; sources that support native range queries should override
+    /// this with something more efficient.
+    fn prices_in_range(
+        &mut self,
+        symbol: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        basis: PriceBasis,
+    ) -> Result<BTreeMap<i64, f64>> {
+        prices_in_range_by_repeated_lookup(self, symbol, start, end, basis)
+    }
+
+    /// Short, human-readable name used to label this source in diagnostics.
+    fn name(&self) -> &'static str;
+}
+
+/// Default, inefficient [`PriceSource::prices_in_range`] implementation:
+/// looks up each minute in the range individually via [`PriceSource::price`].
+fn prices_in_range_by_repeated_lookup<S: PriceSource + ?Sized>(
+    source: &mut S,
+    symbol: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    basis: PriceBasis,
+) -> Result<BTreeMap<i64, f64>> {
+    let mut prices = BTreeMap::new();
+    let mut minute = truncate_to_minute(start);
+    let end_minute = truncate_to_minute(end);
+
+    while minute <= end_minute {
+        let sample = source.price(symbol, minute, basis)?;
+        prices.insert(minute_timestamp_ms(minute), sample.price);
+        minute += Duration::minutes(1);
+    }
+
+    Ok(prices)
+}
+
+/// Truncate a datetime down to the start of its minute.
+fn truncate_to_minute(datetime: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.timestamp_opt(datetime.timestamp() / 60 * 60, 0)
+        .single()
+        .expect("truncating down to a whole minute is always in range")
+}
+
+/// Compute one [`PriceBasis::Vwap`] price per distinct UTC day in
+/// `[start, end]` and broadcast it to every minute of that day, instead of
+/// falling through to [`prices_in_range_by_repeated_lookup`], which would
+/// otherwise re-fetch the identical whole-day vwap once per minute.
+fn vwap_prices_in_range_by_day<F>(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    mut day_vwap: F,
+) -> Result<BTreeMap<i64, f64>>
+where
+    F: FnMut(DateTime<Utc>) -> Result<f64>,
+{
+    let mut prices = BTreeMap::new();
+    let mut minute = truncate_to_minute(start);
+    let end_minute = truncate_to_minute(end);
+
+    while minute <= end_minute {
+        let price = day_vwap(minute)?;
+        let next_day = day_start(minute) + Duration::days(1);
+
+        while minute <= end_minute && minute < next_day {
+            prices.insert(minute_timestamp_ms(minute), price);
+            minute += Duration::minutes(1);
+        }
+    }
+
+    Ok(prices)
+}
+
+/// Minute-truncated UNIX timestamp in milliseconds, used to index
+/// [`PriceSource::prices_in_range`] results.
+pub fn minute_timestamp_ms(datetime: DateTime<Utc>) -> i64 {
+    datetime.timestamp() / 60 * 60000
+}
+
+/// Price source backend selectable from the command line.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SourceKind {
+    Binance,
+    Coinbase,
+}
+
+impl SourceKind {
+    /// Instantiate the [`PriceSource`] this variant refers to.
+    fn into_source(self) -> Box<dyn PriceSource> {
+        match self {
+            SourceKind::Binance => Box::new(BinanceSource::new()),
+            SourceKind::Coinbase => Box::new(CoinbaseSource::new()),
+        }
+    }
+}
+
+/// How the reference price for a reward is derived from the OHLCV data at
+/// that minute.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum PriceBasis {
+    /// The minute's closing price (default).
+    Close,
+    /// The minute's opening price.
+    Open,
+    /// The minute's high price.
+    High,
+    /// The minute's low price.
+    Low,
+    /// Average of the minute's open, high, low and close.
+    Ohlc4,
+    /// Volume-weighted average price over the whole day containing the reward.
+    Vwap,
+}
+
+impl PriceBasis {
+    /// Short, filesystem-safe name used to namespace on-disk cache files.
+    fn cache_key(self) -> &'static str {
+        match self {
+            PriceBasis::Close => "close",
+            PriceBasis::Open => "open",
+            PriceBasis::High => "high",
+            PriceBasis::Low => "low",
+            PriceBasis::Ohlc4 => "ohlc4",
+            PriceBasis::Vwap => "vwap",
+        }
+    }
+}
+
+/// A price lookup result annotated with diagnostics: where it came from and
+/// how long it took, so callers can report fetch throughput and data quality.
+pub struct PriceLookup {
+    pub price: f64,
+    /// `"cache"` if served from the on-disk cache, otherwise the answering
+    /// source's [`PriceSource::name`].
+    pub source: &'static str,
+    /// Time spent in the source that answered the request. Zero for a cache hit.
+    pub latency: StdDuration,
+    /// Set when the answering source had to approximate `price` from a
+    /// nearby minute rather than read it exactly. Always `None` on a cache
+    /// hit, since the cache only ever stores resolved prices.
+    pub anomaly: Option<String>,
+}
+
+impl PriceLookup {
+    /// Whether this lookup was served from the on-disk cache rather than a
+    /// network fetch.
+    pub fn is_cache_hit(&self) -> bool {
+        self.source == CACHE_SOURCE_NAME
+    }
+}
 
-/// Binance Public API price client.
+/// Price client chaining one or more [`PriceSource`]s.
+///
+/// Sources are tried in order; if a source fails (network error, non-success
+/// status, or no usable kline within [`APPROX_WINDOW_MINUTES`] of the
+/// requested minute) the next one in the chain is tried instead of aborting.
+/// This is what lets a Binance outage or geo-block fall back to Coinbase
+/// transparently. A range fetch that comes back empty or with gaps counts as
+/// a success and does not move on to the next source.
 pub struct PriceClient {
-    client: Client,
+    sources: Vec<Box<dyn PriceSource>>,
+    cache_dir: Option<PathBuf>,
+    caches: HashMap<String, PriceCache>,
 }
 
 impl PriceClient {
+    /// Create a client with the default source chain: Binance first, falling
+    /// back to Coinbase.
+    pub fn new() -> Self {
+        Self {
+            sources: vec![Box::new(BinanceSource::new()), Box::new(CoinbaseSource::new())],
+            cache_dir: None,
+            caches: HashMap::new(),
+        }
+    }
+
+    /// Create a client restricted to a single, explicitly selected source.
+    pub fn with_source(source: SourceKind) -> Self {
+        Self {
+            sources: vec![source.into_source()],
+            cache_dir: None,
+            caches: HashMap::new(),
+        }
+    }
+
+    /// Enable an on-disk price cache rooted at `cache_dir`, consulted before
+    /// any network request and populated with newly fetched prices.
+    pub fn with_cache(mut self, cache_dir: PathBuf) -> Self {
+        self.cache_dir = Some(cache_dir);
+        self
+    }
+
+    /// Request symbol price, trying the cache first, then each configured
+    /// source in turn.
+    pub fn price(&mut self, symbol: &str, datetime: DateTime<Utc>, basis: PriceBasis) -> Result<f64> {
+        Ok(self.price_with_diagnostics(symbol, datetime, basis)?.price)
+    }
+
+    /// Like [`PriceClient::price`], but also reports which source answered
+    /// the request (or the cache) and how long it took.
+    pub fn price_with_diagnostics(
+        &mut self,
+        symbol: &str,
+        datetime: DateTime<Utc>,
+        basis: PriceBasis,
+    ) -> Result<PriceLookup> {
+        let minute_ms = minute_timestamp_ms(datetime);
+
+        if let Some(price) = self.cache_for(symbol, basis)?.and_then(|cache| cache.get(minute_ms)) {
+            return Ok(PriceLookup {
+                price,
+                source: CACHE_SOURCE_NAME,
+                latency: StdDuration::ZERO,
+                anomaly: None,
+            });
+        }
+
+        let mut last_err = None;
+
+        for source in &mut self.sources {
+            let started = Instant::now();
+            match source.price(symbol, datetime, basis) {
+                Ok(sample) => {
+                    let latency = started.elapsed();
+                    if let Some(cache) = self.cache_for(symbol, basis)? {
+                        cache.insert(minute_ms, sample.price);
+                    }
+                    return Ok(PriceLookup {
+                        price: sample.price,
+                        source: source.name(),
+                        latency,
+                        anomaly: sample.anomaly,
+                    });
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| anyhow!("no price sources configured")))
+    }
+
+    /// Fetch prices for every minute in `[start, end]`, trying each
+    /// configured source in turn, indexed by minute-truncated timestamp in
+    /// milliseconds (see [`minute_timestamp_ms`]).
+    ///
+    /// `needed_minutes` are the minute timestamps (in milliseconds) the
+    /// caller actually intends to look up, e.g. the reward rows driving the
+    /// `[start, end]` span. If the cache already holds all of them, they are
+    /// served from there without hitting the network; otherwise the whole
+    /// range is fetched from a source and cached in one go. Checking only
+    /// the needed minutes (rather than every minute in the range) matters
+    /// because sources legitimately leave gaps for minutes with no trades,
+    /// and those gaps shouldn't force a re-fetch of minutes nobody asked
+    /// for.
+    pub fn prices_in_range(
+        &mut self,
+        symbol: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        basis: PriceBasis,
+        needed_minutes: &BTreeSet<i64>,
+    ) -> Result<BTreeMap<i64, f64>> {
+        if let Some(prices) = self.cached_minutes(symbol, basis, needed_minutes)? {
+            return Ok(prices);
+        }
+
+        let mut last_err = None;
+
+        for source in &mut self.sources {
+            match source.prices_in_range(symbol, start, end, basis) {
+                Ok(prices) => {
+                    if let Some(cache) = self.cache_for(symbol, basis)? {
+                        for (&minute_ms, &price) in &prices {
+                            cache.insert(minute_ms, price);
+                        }
+                    }
+                    return Ok(prices);
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| anyhow!("no price sources configured")))
+    }
+
+    /// Serve `needed_minutes` entirely from the cache, or `None` if any of
+    /// them is missing.
+    fn cached_minutes(
+        &mut self,
+        symbol: &str,
+        basis: PriceBasis,
+        needed_minutes: &BTreeSet<i64>,
+    ) -> Result<Option<BTreeMap<i64, f64>>> {
+        let Some(cache) = self.cache_for(symbol, basis)? else {
+            return Ok(None);
+        };
+
+        let mut prices = BTreeMap::new();
+
+        for &minute_ms in needed_minutes {
+            match cache.get(minute_ms) {
+                Some(price) => {
+                    prices.insert(minute_ms, price);
+                }
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some(prices))
+    }
+
+    /// Lazily load and return the cache for `(symbol, basis)`, or `None` if
+    /// caching is disabled.
+    fn cache_for(&mut self, symbol: &str, basis: PriceBasis) -> Result<Option<&mut PriceCache>> {
+        let Some(dir) = &self.cache_dir else {
+            return Ok(None);
+        };
+
+        let name = format!("{symbol}_{}", basis.cache_key());
+        if !self.caches.contains_key(&name) {
+            let cache = PriceCache::load(dir, &name)?;
+            self.caches.insert(name.clone(), cache);
+        }
+
+        Ok(self.caches.get_mut(&name))
+    }
+}
+
+impl Default for PriceClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A single OHLCV field, or a derived combination of them, to read out of a
+/// kline/candle entry. Unlike [`PriceBasis`], `Vwap` has no field counterpart
+/// here: it is computed from a whole day of entries rather than read off one.
+#[derive(Clone, Copy, Debug)]
+enum OhlcField {
+    Open,
+    High,
+    Low,
+    Close,
+    Ohlc4,
+}
+
+impl TryFrom<PriceBasis> for OhlcField {
+    type Error = anyhow::Error;
+
+    fn try_from(basis: PriceBasis) -> Result<OhlcField> {
+        match basis {
+            PriceBasis::Open => Ok(OhlcField::Open),
+            PriceBasis::High => Ok(OhlcField::High),
+            PriceBasis::Low => Ok(OhlcField::Low),
+            PriceBasis::Close => Ok(OhlcField::Close),
+            PriceBasis::Ohlc4 => Ok(OhlcField::Ohlc4),
+            PriceBasis::Vwap => Err(anyhow!("vwap has no single-entry field counterpart")),
+        }
+    }
+}
+
+/// Start of the UTC day containing `datetime`.
+fn day_start(datetime: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&NaiveDateTime::new(datetime.date_naive(), NaiveTime::MIN))
+}
+
+/// Binance Public API price source.
+pub struct BinanceSource {
+    client: Client,
+}
+
+impl BinanceSource {
     /// Create new instance.
     pub fn new() -> Self {
         Self {
@@ -22,8 +441,12 @@ impl PriceClient {
         }
     }
 
-    /// Request symbol price.
-    pub fn price(&mut self, symbol: &str, datetime: DateTime<Utc>) -> Result<f64> {
+    fn single_minute_price(
+        &mut self,
+        symbol: &str,
+        datetime: DateTime<Utc>,
+        field: OhlcField,
+    ) -> Result<PriceSample> {
         // Get UNIX timestamp in milliseconds.
         let time_ms = datetime.timestamp() * 1000;
 
@@ -32,36 +455,307 @@ impl PriceClient {
         let start_time_ms = time_ms / 60000 * 60000;
 
         let url = format!(
-            "{BASE_URL}/api/v3/klines?symbol={symbol}&interval=1m&startTime={start_time_ms}&limit=1"
+            "{BINANCE_BASE_URL}/api/v3/klines?symbol={symbol}&interval=1m&startTime={start_time_ms}&limit=1"
+        );
+
+        let (status, headers, body) = fetch(&self.client, &url)?;
+
+        if !status.is_success() {
+            return Err(anyhow!(request_context(&url, status, &headers, &body)));
+        }
+
+        let payload: Vec<Vec<serde_json::Value>> = serde_json::from_str(&body)
+            .with_context(|| request_context(&url, status, &headers, &body))?;
+
+        match extract_field_from_klines(payload, start_time_ms, field) {
+            Ok(price) => Ok(PriceSample {
+                price,
+                anomaly: None,
+            }),
+            Err(e) => self
+                .approximate_minute_price(symbol, start_time_ms, field)
+                .with_context(|| format!("exact minute has no usable kline ({e}), and approximation also failed")),
+        }
+    }
+
+    /// Approximate a minute's price from the nearest kline within
+    /// [`APPROX_WINDOW_MINUTES`] of `start_time_ms`, for a minute Binance
+    /// returned no kline for, or a mismatched timestamp for.
+    fn approximate_minute_price(
+        &mut self,
+        symbol: &str,
+        start_time_ms: i64,
+        field: OhlcField,
+    ) -> Result<PriceSample> {
+        let window_ms = APPROX_WINDOW_MINUTES * 60_000;
+        let window_start_ms = start_time_ms - window_ms;
+        let window_end_ms = start_time_ms + window_ms;
+        let window_limit = APPROX_WINDOW_MINUTES * 2 + 1;
+
+        let url = format!(
+            "{BINANCE_BASE_URL}/api/v3/klines?symbol={symbol}&interval=1m&startTime={window_start_ms}&endTime={window_end_ms}&limit={window_limit}"
         );
 
-        let res = self.client.get(&url).send()?;
+        let (status, headers, body) = fetch(&self.client, &url)?;
+
+        if !status.is_success() {
+            return Err(anyhow!(request_context(&url, status, &headers, &body)));
+        }
+
+        let klines: Vec<Vec<serde_json::Value>> = serde_json::from_str(&body)
+            .with_context(|| request_context(&url, status, &headers, &body))?;
+
+        let nearest = klines
+            .iter()
+            .filter(|kline| kline.len() == KLINE_FIELDS_NUM)
+            .filter_map(|kline| kline[0].as_i64().map(|open_ms| (open_ms, kline)))
+            .min_by_key(|(open_ms, _)| (open_ms - start_time_ms).abs());
+
+        let Some((open_ms, kline)) = nearest else {
+            return Err(anyhow!(
+                "no kline found within {APPROX_WINDOW_MINUTES} minutes of the requested minute"
+            ));
+        };
+
+        let price = extract_ohlc_field(kline, field, 1, 2, 3, 4)?;
+        let offset_minutes = (open_ms - start_time_ms) / 60_000;
+
+        Ok(PriceSample {
+            price,
+            anomaly: Some(format!(
+                "approximated from {offset_minutes}min away kline"
+            )),
+        })
+    }
+
+    /// Compute the volume-weighted average price over the whole UTC day
+    /// containing `datetime`, paginating the klines endpoint in
+    /// `KLINE_PAGE_LIMIT`-sized requests since a day is 1440 minutes, above
+    /// Binance's 1000-entry-per-request cap.
+    fn day_vwap_price(&mut self, symbol: &str, datetime: DateTime<Utc>) -> Result<f64> {
+        let day_end = day_start(datetime) + Duration::days(1) - Duration::minutes(1);
+
+        let mut cursor_ms = day_start(datetime).timestamp() * 1000;
+        let end_ms = day_end.timestamp() * 1000;
+
+        let mut weighted_sum = 0.0;
+        let mut volume_sum = 0.0;
+
+        while cursor_ms <= end_ms {
+            let url = format!(
+                "{BINANCE_BASE_URL}/api/v3/klines?symbol={symbol}&interval=1m&startTime={cursor_ms}&endTime={end_ms}&limit={KLINE_PAGE_LIMIT}"
+            );
+
+            let (status, headers, body) = fetch(&self.client, &url)?;
+
+            if !status.is_success() {
+                return Err(anyhow!(request_context(&url, status, &headers, &body)));
+            }
+
+            let klines: Vec<Vec<serde_json::Value>> = serde_json::from_str(&body)
+                .with_context(|| request_context(&url, status, &headers, &body))?;
+
+            if klines.is_empty() {
+                break;
+            }
+
+            let page_len = klines.len();
+            let last_open_ms =
+                accumulate_vwap_from_klines(&klines, &mut weighted_sum, &mut volume_sum)
+                    .with_context(|| request_context(&url, status, &headers, &body))?;
+
+            if page_len < KLINE_PAGE_LIMIT {
+                break;
+            }
+
+            // `endTime` is inclusive, so a full page may return the same
+            // last kline again next time around unless we step past it.
+            cursor_ms = last_open_ms + 60_000;
+        }
 
+        if volume_sum == 0.0 {
+            return Err(anyhow!(
+                "no minutes with non-zero volume found to compute vwap"
+            ));
+        }
+
+        Ok(weighted_sum / volume_sum)
+    }
+}
+
+impl Default for BinanceSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceSource for BinanceSource {
+    fn price(
+        &mut self,
+        symbol: &str,
+        datetime: DateTime<Utc>,
+        basis: PriceBasis,
+    ) -> Result<PriceSample> {
+        match OhlcField::try_from(basis) {
+            Ok(field) => self.single_minute_price(symbol, datetime, field),
+            Err(_) => Ok(PriceSample {
+                price: self.day_vwap_price(symbol, datetime)?,
+                anomaly: None,
+            }),
+        }
+    }
+
+    fn prices_in_range(
+        &mut self,
+        symbol: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        basis: PriceBasis,
+    ) -> Result<BTreeMap<i64, f64>> {
+        // `vwap` aggregates a whole day per reward rather than reading a
+        // single field per minute, so it doesn't fit the paginated klines
+        // fetch below; broadcast one vwap fetch per distinct day instead,
+        // rather than repeating the identical whole-day fetch every minute.
+        match OhlcField::try_from(basis) {
+            Ok(field) => self.paginated_prices_in_range(symbol, start, end, field),
+            Err(_) => vwap_prices_in_range_by_day(start, end, |datetime| {
+                self.day_vwap_price(symbol, datetime)
+            }),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "binance"
+    }
+}
+
+impl BinanceSource {
+    /// Fetch prices for every minute in `[start, end]` by paginating the
+    /// klines endpoint with `startTime`/`endTime`/`limit=1000` instead of
+    /// issuing one request per minute.
+    fn paginated_prices_in_range(
+        &mut self,
+        symbol: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        field: OhlcField,
+    ) -> Result<BTreeMap<i64, f64>> {
+        let mut prices = BTreeMap::new();
+
+        let mut cursor_ms = minute_timestamp_ms(start);
+        let end_ms = minute_timestamp_ms(end);
+
+        while cursor_ms <= end_ms {
+            let url = format!(
+                "{BINANCE_BASE_URL}/api/v3/klines?symbol={symbol}&interval=1m&startTime={cursor_ms}&endTime={end_ms}&limit={KLINE_PAGE_LIMIT}"
+            );
+
+            let (status, headers, body) = fetch(&self.client, &url)?;
+
+            if !status.is_success() {
+                return Err(anyhow!(request_context(&url, status, &headers, &body)));
+            }
+
+            let klines: Vec<Vec<serde_json::Value>> = serde_json::from_str(&body)
+                .with_context(|| request_context(&url, status, &headers, &body))?;
+
+            if klines.is_empty() {
+                break;
+            }
+
+            let page_len = klines.len();
+            let last_open_ms = insert_klines(&mut prices, klines, field)
+                .with_context(|| request_context(&url, status, &headers, &body))?;
+
+            if page_len < KLINE_PAGE_LIMIT {
+                break;
+            }
+
+            // Binance's `endTime` is inclusive, so a full page may return the
+            // same last kline again next time around unless we step past it.
+            cursor_ms = last_open_ms + 60_000;
+        }
+
+        Ok(prices)
+    }
+}
+
+/// Insert every kline's requested field into `prices`, keyed by its open
+/// time. Returns the open time of the last inserted entry, used to advance
+/// the pagination cursor. Klines that Binance skips (e.g. no trades in that
+/// minute) simply leave a gap in `prices` for the caller to fall back on.
+fn insert_klines(
+    prices: &mut BTreeMap<i64, f64>,
+    klines: Vec<Vec<serde_json::Value>>,
+    field: OhlcField,
+) -> Result<i64> {
+    let mut last_open_ms = None;
+
+    for kline in &klines {
+        if kline.len() != KLINE_FIELDS_NUM {
+            return Err(anyhow!(
+                "price (kline) entry contains {} fields instead of {KLINE_FIELDS_NUM}",
+                kline.len(),
+            ));
+        }
+
+        let open_ms = kline[0]
+            .as_i64()
+            .ok_or(anyhow!("timestamp entry is not a number"))?;
+        let price = extract_ohlc_field(kline, field, 1, 2, 3, 4)?;
+
+        prices.insert(open_ms, price);
+        last_open_ms = Some(open_ms);
+    }
+
+    last_open_ms.ok_or_else(|| anyhow!("kline page unexpectedly empty"))
+}
+
+/// Send a GET request and return its status, headers and body, surfacing a
+/// transport error with as much context as is available.
+///
+/// Transparently retries on a `429` (Too Many Requests) or `418` (Binance's
+/// "IP banned" status), honouring a `Retry-After` header when present, so
+/// that a large report doesn't abort midway due to rate limiting.
+fn fetch(client: &Client, url: &str) -> Result<(StatusCode, HeaderMap<HeaderValue>, String)> {
+    let mut attempt = 0;
+
+    loop {
+        let res = client.get(url).header("User-Agent", "dotgain").send()?;
         let status = res.status();
         let headers = res.headers().clone();
+
+        if is_rate_limited(status) && attempt < MAX_RATE_LIMIT_RETRIES {
+            let wait = retry_after_secs(&headers).unwrap_or(DEFAULT_RETRY_AFTER_SECS);
+            thread::sleep(StdDuration::from_secs(wait));
+            attempt += 1;
+            continue;
+        }
+
         let body = match res.text() {
             Ok(body) => body,
-            Err(e) => return Err(e).context(request_context_no_body(&url, status, &headers)),
+            Err(e) => return Err(e).context(request_context_no_body(url, status, &headers)),
         };
 
-        if status.is_success() {
-            Ok(extract_price_from_body(&body, start_time_ms)
-                .with_context(|| request_context(&url, status, &headers, &body))?)
-        } else {
-            Err(anyhow!(request_context(&url, status, &headers, &body)))
-        }
+        return Ok((status, headers, body));
     }
 }
 
-/// Parse kline response body and extract close price.
-fn extract_price_from_body(body: &str, start_time_ms: i64) -> Result<f64> {
-    Ok(extract_price_from_payload(
-        serde_json::from_str(body)?,
-        start_time_ms,
-    )?)
+/// Whether `status` indicates the request was rate-limited.
+fn is_rate_limited(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.as_u16() == 418
+}
+
+/// Parse the number of seconds to wait from a `Retry-After` header.
+fn retry_after_secs(headers: &HeaderMap<HeaderValue>) -> Option<u64> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
 }
 
-/// Extract close price from kline response containing at least one kline entry.
+/// Extract the requested field from a kline response containing exactly the
+/// requested minute.
 // Example respone:
 // [
 //   [
@@ -81,9 +775,10 @@ fn extract_price_from_body(body: &str, start_time_ms: i64) -> Result<f64> {
 // ]
 //
 // See https://binance-docs.github.io/apidocs/spot/en/#kline-candlestick-data
-fn extract_price_from_payload(
+fn extract_field_from_klines(
     payload: Vec<Vec<serde_json::Value>>,
     start_time_ms: i64,
+    field: OhlcField,
 ) -> Result<f64> {
     if payload.is_empty() {
         return Err(anyhow!(
@@ -91,14 +786,16 @@ fn extract_price_from_payload(
         ));
     }
 
-    if payload[0].len() != KLINE_FIELDS_NUM {
+    let kline = &payload[0];
+
+    if kline.len() != KLINE_FIELDS_NUM {
         return Err(anyhow!(
             "price (kline) entry contains {} fields instead of {KLINE_FIELDS_NUM}",
-            payload[0].len(),
+            kline.len(),
         ));
     }
 
-    let returned_time_ms = payload[0][0]
+    let returned_time_ms = kline[0]
         .as_i64()
         .ok_or(anyhow!("timestamp entry is not a number"))?;
     if returned_time_ms != start_time_ms {
@@ -123,13 +820,472 @@ fn extract_price_from_payload(
         };
     }
 
-    let price_str = payload[0][4]
+    extract_ohlc_field(kline, field, 1, 2, 3, 4)
+}
+
+/// Read `field` out of an OHLCV entry given the indices of its open, high,
+/// low and close fields (as strings, matching both Binance klines and
+/// Coinbase candles).
+fn extract_ohlc_field(
+    entry: &[serde_json::Value],
+    field: OhlcField,
+    open_idx: usize,
+    high_idx: usize,
+    low_idx: usize,
+    close_idx: usize,
+) -> Result<f64> {
+    match field {
+        OhlcField::Open => str_field_f64(entry, open_idx),
+        OhlcField::High => str_field_f64(entry, high_idx),
+        OhlcField::Low => str_field_f64(entry, low_idx),
+        OhlcField::Close => str_field_f64(entry, close_idx),
+        OhlcField::Ohlc4 => {
+            let open = str_field_f64(entry, open_idx)?;
+            let high = str_field_f64(entry, high_idx)?;
+            let low = str_field_f64(entry, low_idx)?;
+            let close = str_field_f64(entry, close_idx)?;
+            Ok((open + high + low + close) / 4.0)
+        }
+    }
+}
+
+/// Parse a string-valued OHLCV field at `index` into an `f64`.
+fn str_field_f64(entry: &[serde_json::Value], index: usize) -> Result<f64> {
+    let value_str = entry[index]
         .as_str()
-        .ok_or(anyhow!("price entry is not a string"))?;
-    let price = price_str
+        .ok_or(anyhow!("field {index} is not a string"))?;
+    value_str
         .parse::<f64>()
-        .with_context(|| format!("cannot convert price entry \"{price_str}\" to number"))?;
-    Ok(price)
+        .with_context(|| format!("cannot convert field {index} value \"{value_str}\" to number"))
+}
+
+/// Fold a page of klines into running vwap accumulators (`sum(typical_price_i
+/// * volume_i)` and `sum(volume_i)`), skipping minutes with zero volume.
+/// Returns the open time of the last entry, used to advance a pagination
+/// cursor the same way [`insert_klines`] does.
+fn accumulate_vwap_from_klines(
+    klines: &[Vec<serde_json::Value>],
+    weighted_sum: &mut f64,
+    volume_sum: &mut f64,
+) -> Result<i64> {
+    let mut last_open_ms = None;
+
+    for kline in klines {
+        if kline.len() != KLINE_FIELDS_NUM {
+            return Err(anyhow!(
+                "price (kline) entry contains {} fields instead of {KLINE_FIELDS_NUM}",
+                kline.len(),
+            ));
+        }
+
+        let open_ms = kline[0]
+            .as_i64()
+            .ok_or(anyhow!("timestamp entry is not a number"))?;
+
+        let volume = str_field_f64(kline, 5)?;
+        if volume != 0.0 {
+            let high = str_field_f64(kline, 2)?;
+            let low = str_field_f64(kline, 3)?;
+            let close = str_field_f64(kline, 4)?;
+            let typical_price = (high + low + close) / 3.0;
+
+            *weighted_sum += typical_price * volume;
+            *volume_sum += volume;
+        }
+
+        last_open_ms = Some(open_ms);
+    }
+
+    last_open_ms.ok_or_else(|| anyhow!("kline page unexpectedly empty"))
+}
+
+/// Coinbase Exchange historic candle price source.
+///
+/// Used as a fallback for pairs or time ranges where Binance is unavailable
+/// (e.g. geo-blocked jurisdictions) or has gaps.
+pub struct CoinbaseSource {
+    client: Client,
+}
+
+impl CoinbaseSource {
+    /// Create new instance.
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+        }
+    }
+
+    fn single_candle_price(
+        &mut self,
+        symbol: &str,
+        datetime: DateTime<Utc>,
+        field: OhlcField,
+    ) -> Result<PriceSample> {
+        let product = to_coinbase_product(symbol)?;
+
+        // Candles are bucketed by the start of their granularity window, so
+        // round down to the nearest minute just like the Binance source.
+        let start = datetime.timestamp() / 60 * 60;
+        let end = start + 60;
+
+        let url = format!(
+            "{COINBASE_BASE_URL}/products/{product}/candles?granularity=60&start={start}&end={end}"
+        );
+
+        let (status, headers, body) = fetch(&self.client, &url)?;
+
+        if !status.is_success() {
+            return Err(anyhow!(request_context(&url, status, &headers, &body)));
+        }
+
+        match extract_field_from_candle_body(&body, start, field) {
+            Ok(price) => Ok(PriceSample {
+                price,
+                anomaly: None,
+            }),
+            Err(e) => self
+                .approximate_candle_price(symbol, start, field)
+                .with_context(|| format!("exact minute has no usable candle ({e}), and approximation also failed")),
+        }
+    }
+
+    /// Approximate a minute's price from the nearest candle within
+    /// [`APPROX_WINDOW_MINUTES`] of `start_s`, for a minute Coinbase
+    /// returned no candle for.
+    fn approximate_candle_price(
+        &mut self,
+        symbol: &str,
+        start_s: i64,
+        field: OhlcField,
+    ) -> Result<PriceSample> {
+        let product = to_coinbase_product(symbol)?;
+
+        let window_s = APPROX_WINDOW_MINUTES * 60;
+        let window_start = start_s - window_s;
+        let window_end = start_s + 60 + window_s;
+
+        let url = format!(
+            "{COINBASE_BASE_URL}/products/{product}/candles?granularity=60&start={window_start}&end={window_end}"
+        );
+
+        let (status, headers, body) = fetch(&self.client, &url)?;
+
+        if !status.is_success() {
+            return Err(anyhow!(request_context(&url, status, &headers, &body)));
+        }
+
+        let candles: Vec<Vec<serde_json::Value>> = serde_json::from_str(&body)
+            .with_context(|| request_context(&url, status, &headers, &body))?;
+
+        let nearest = candles
+            .iter()
+            .filter(|candle| candle.len() == CANDLE_FIELDS_NUM)
+            .filter_map(|candle| candle[0].as_i64().map(|time_s| (time_s, candle)))
+            .min_by_key(|(time_s, _)| (time_s - start_s).abs());
+
+        let Some((time_s, candle)) = nearest else {
+            return Err(anyhow!(
+                "no candle found within {APPROX_WINDOW_MINUTES} minutes of the requested minute"
+            ));
+        };
+
+        let price = extract_candle_field(candle, field)?;
+        let offset_minutes = (time_s - start_s) / 60;
+
+        Ok(PriceSample {
+            price,
+            anomaly: Some(format!(
+                "approximated from {offset_minutes}min away candle"
+            )),
+        })
+    }
+
+    /// Compute the volume-weighted average price over the whole UTC day
+    /// containing `datetime`, paginating the candles endpoint in
+    /// `CANDLE_PAGE_LIMIT`-sized windows since a day is 1440 one-minute
+    /// candles, above Coinbase's 300-candles-per-request cap.
+    fn day_vwap_price(&mut self, symbol: &str, datetime: DateTime<Utc>) -> Result<f64> {
+        let product = to_coinbase_product(symbol)?;
+
+        let day_end = day_start(datetime) + Duration::days(1) - Duration::minutes(1);
+        let mut cursor = day_start(datetime).timestamp();
+        let end = day_end.timestamp();
+
+        let mut weighted_sum = 0.0;
+        let mut volume_sum = 0.0;
+
+        while cursor <= end {
+            let page_end = std::cmp::min(cursor + (CANDLE_PAGE_LIMIT as i64 - 1) * 60, end);
+
+            let url = format!(
+                "{COINBASE_BASE_URL}/products/{product}/candles?granularity=60&start={cursor}&end={page_end}"
+            );
+
+            let (status, headers, body) = fetch(&self.client, &url)?;
+
+            if !status.is_success() {
+                return Err(anyhow!(request_context(&url, status, &headers, &body)));
+            }
+
+            let candles: Vec<Vec<serde_json::Value>> = serde_json::from_str(&body)
+                .with_context(|| request_context(&url, status, &headers, &body))?;
+
+            accumulate_vwap_from_candles(&candles, &mut weighted_sum, &mut volume_sum)
+                .with_context(|| request_context(&url, status, &headers, &body))?;
+
+            cursor = page_end + 60;
+        }
+
+        if volume_sum == 0.0 {
+            return Err(anyhow!(
+                "no minutes with non-zero volume found to compute vwap"
+            ));
+        }
+
+        Ok(weighted_sum / volume_sum)
+    }
+
+    /// Fetch prices for every minute in `[start, end]` by paginating the
+    /// candles endpoint in `CANDLE_PAGE_LIMIT`-sized windows instead of
+    /// issuing one request per minute.
+    fn paginated_prices_in_range(
+        &mut self,
+        symbol: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        field: OhlcField,
+    ) -> Result<BTreeMap<i64, f64>> {
+        let product = to_coinbase_product(symbol)?;
+        let mut prices = BTreeMap::new();
+
+        let mut cursor = truncate_to_minute(start).timestamp();
+        let end_s = truncate_to_minute(end).timestamp();
+
+        while cursor <= end_s {
+            let page_end = std::cmp::min(cursor + (CANDLE_PAGE_LIMIT as i64 - 1) * 60, end_s);
+
+            let url = format!(
+                "{COINBASE_BASE_URL}/products/{product}/candles?granularity=60&start={cursor}&end={page_end}"
+            );
+
+            let (status, headers, body) = fetch(&self.client, &url)?;
+
+            if !status.is_success() {
+                return Err(anyhow!(request_context(&url, status, &headers, &body)));
+            }
+
+            let candles: Vec<Vec<serde_json::Value>> = serde_json::from_str(&body)
+                .with_context(|| request_context(&url, status, &headers, &body))?;
+
+            insert_candles(&mut prices, candles, field)
+                .with_context(|| request_context(&url, status, &headers, &body))?;
+
+            cursor = page_end + 60;
+        }
+
+        Ok(prices)
+    }
+}
+
+impl Default for CoinbaseSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceSource for CoinbaseSource {
+    fn price(
+        &mut self,
+        symbol: &str,
+        datetime: DateTime<Utc>,
+        basis: PriceBasis,
+    ) -> Result<PriceSample> {
+        match OhlcField::try_from(basis) {
+            Ok(field) => self.single_candle_price(symbol, datetime, field),
+            Err(_) => Ok(PriceSample {
+                price: self.day_vwap_price(symbol, datetime)?,
+                anomaly: None,
+            }),
+        }
+    }
+
+    fn prices_in_range(
+        &mut self,
+        symbol: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        basis: PriceBasis,
+    ) -> Result<BTreeMap<i64, f64>> {
+        match OhlcField::try_from(basis) {
+            Ok(field) => self.paginated_prices_in_range(symbol, start, end, field),
+            Err(_) => vwap_prices_in_range_by_day(start, end, |datetime| {
+                self.day_vwap_price(symbol, datetime)
+            }),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "coinbase"
+    }
+}
+
+/// Quote currencies recognized when splitting a Binance-style symbol (e.g.
+/// `DOTEUR`, `DOTUSDT`) into a Coinbase product id. Checked longest-first so
+/// a 4-letter quote like `USDT`/`USDC` isn't mis-split as a 3-letter suffix
+/// (e.g. `DOTUSDT` -> `DOTU-SDT`).
+const COINBASE_QUOTE_CURRENCIES: &[&str] = &["USDT", "USDC", "USD", "EUR", "GBP", "BTC", "ETH"];
+
+/// Convert a Binance-style symbol (e.g. `DOTEUR`) into a Coinbase product id
+/// (e.g. `DOT-EUR`), recognizing the quote currency from
+/// [`COINBASE_QUOTE_CURRENCIES`] rather than assuming a fixed 3-letter
+/// suffix.
+fn to_coinbase_product(symbol: &str) -> Result<String> {
+    let quote = COINBASE_QUOTE_CURRENCIES
+        .iter()
+        .find(|quote| symbol.len() > quote.len() && symbol.ends_with(**quote))
+        .ok_or_else(|| {
+            anyhow!(
+                "cannot determine Coinbase quote currency for symbol \"{symbol}\" \
+                 (expected one of {COINBASE_QUOTE_CURRENCIES:?} as a suffix)"
+            )
+        })?;
+    let (base, quote) = symbol.split_at(symbol.len() - quote.len());
+    Ok(format!("{base}-{quote}"))
+}
+
+/// Parse candle response body and extract the requested field.
+fn extract_field_from_candle_body(
+    body: &str,
+    start_time_s: i64,
+    field: OhlcField,
+) -> Result<f64> {
+    Ok(extract_field_from_candles(
+        serde_json::from_str(body)?,
+        start_time_s,
+        field,
+    )?)
+}
+
+/// Extract the requested field from a Coinbase candle response containing at
+/// least one candle.
+//
+// Example response:
+// [
+//   [ 1415398768, 0.32, 4.2, 0.35, 4.2, 12.3 ],
+//   ...
+// ]
+// where each entry is [time, low, high, open, close, volume].
+//
+// See https://docs.cloud.coinbase.com/exchange/reference/exchangerestapi_getproductcandles
+fn extract_field_from_candles(
+    payload: Vec<Vec<serde_json::Value>>,
+    start_time_s: i64,
+    field: OhlcField,
+) -> Result<f64> {
+    if payload.is_empty() {
+        return Err(anyhow!(
+            "response must contain at least one price (candle) entry"
+        ));
+    }
+
+    let candle = payload
+        .iter()
+        .find(|candle| candle.first().and_then(|time| time.as_i64()) == Some(start_time_s))
+        .ok_or_else(|| anyhow!("no candle for requested timestamp {start_time_s} in response"))?;
+
+    if candle.len() != CANDLE_FIELDS_NUM {
+        return Err(anyhow!(
+            "price (candle) entry contains {} fields instead of {CANDLE_FIELDS_NUM}",
+            candle.len(),
+        ));
+    }
+
+    extract_candle_field(candle, field)
+}
+
+/// Read `field` out of a Coinbase candle entry, whose fields are numbers
+/// rather than strings like Binance's.
+fn extract_candle_field(candle: &[serde_json::Value], field: OhlcField) -> Result<f64> {
+    match field {
+        OhlcField::Open => num_field_f64(candle, 3),
+        OhlcField::High => num_field_f64(candle, 2),
+        OhlcField::Low => num_field_f64(candle, 1),
+        OhlcField::Close => num_field_f64(candle, 4),
+        OhlcField::Ohlc4 => {
+            let open = num_field_f64(candle, 3)?;
+            let high = num_field_f64(candle, 2)?;
+            let low = num_field_f64(candle, 1)?;
+            let close = num_field_f64(candle, 4)?;
+            Ok((open + high + low + close) / 4.0)
+        }
+    }
+}
+
+/// Parse a numeric candle field at `index` into an `f64`.
+fn num_field_f64(entry: &[serde_json::Value], index: usize) -> Result<f64> {
+    entry[index]
+        .as_f64()
+        .ok_or_else(|| anyhow!("candle field {index} is not a number"))
+}
+
+/// Fold a page of candles into running vwap accumulators (`sum(typical_price_i
+/// * volume_i)` and `sum(volume_i)`), skipping minutes with zero volume. A
+/// page with no candles (no trades in that window) is not an error.
+fn accumulate_vwap_from_candles(
+    candles: &[Vec<serde_json::Value>],
+    weighted_sum: &mut f64,
+    volume_sum: &mut f64,
+) -> Result<()> {
+    for candle in candles {
+        if candle.len() != CANDLE_FIELDS_NUM {
+            return Err(anyhow!(
+                "price (candle) entry contains {} fields instead of {CANDLE_FIELDS_NUM}",
+                candle.len(),
+            ));
+        }
+
+        let volume = num_field_f64(candle, 5)?;
+        if volume == 0.0 {
+            continue;
+        }
+
+        let low = num_field_f64(candle, 1)?;
+        let high = num_field_f64(candle, 2)?;
+        let close = num_field_f64(candle, 4)?;
+        let typical_price = (high + low + close) / 3.0;
+
+        *weighted_sum += typical_price * volume;
+        *volume_sum += volume;
+    }
+
+    Ok(())
+}
+
+/// Insert every candle's requested field into `prices`, keyed by its
+/// timestamp in milliseconds. A page with no candles (no trades in that
+/// window) simply leaves a gap in `prices` for the caller to fall back on.
+fn insert_candles(
+    prices: &mut BTreeMap<i64, f64>,
+    candles: Vec<Vec<serde_json::Value>>,
+    field: OhlcField,
+) -> Result<()> {
+    for candle in &candles {
+        if candle.len() != CANDLE_FIELDS_NUM {
+            return Err(anyhow!(
+                "price (candle) entry contains {} fields instead of {CANDLE_FIELDS_NUM}",
+                candle.len(),
+            ));
+        }
+
+        let time_s = candle[0]
+            .as_i64()
+            .ok_or(anyhow!("timestamp entry is not a number"))?;
+        let price = extract_candle_field(candle, field)?;
+
+        prices.insert(time_s * 1000, price);
+    }
+
+    Ok(())
 }
 
 /// Format detailed information about response for error reporting.
@@ -161,3 +1317,156 @@ fn request_context_no_body(
         status, headers,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn kline(
+        open_ms: i64,
+        open: &str,
+        high: &str,
+        low: &str,
+        close: &str,
+        volume: &str,
+    ) -> Vec<serde_json::Value> {
+        vec![
+            json!(open_ms),
+            json!(open),
+            json!(high),
+            json!(low),
+            json!(close),
+            json!(volume),
+            json!(open_ms + 59_999),
+            json!("0"),
+            json!(1),
+            json!("0"),
+            json!("0"),
+            json!("0"),
+        ]
+    }
+
+    #[test]
+    fn insert_klines_indexes_by_open_time() {
+        let klines = vec![
+            kline(60_000_000, "1.0", "1.2", "0.9", "1.1", "10"),
+            kline(60_060_000, "1.1", "1.3", "1.0", "1.2", "20"),
+        ];
+
+        let mut prices = BTreeMap::new();
+        let last = insert_klines(&mut prices, klines, OhlcField::Close).unwrap();
+
+        assert_eq!(last, 60_060_000);
+        assert_eq!(prices.get(&60_000_000), Some(&1.1));
+        assert_eq!(prices.get(&60_060_000), Some(&1.2));
+    }
+
+    #[test]
+    fn insert_klines_leaves_gaps_for_missing_minutes() {
+        // Binance skipped the minute at 60_060_000 entirely (no trades).
+        let klines = vec![
+            kline(60_000_000, "1.0", "1.2", "0.9", "1.1", "10"),
+            kline(60_120_000, "1.2", "1.4", "1.1", "1.3", "5"),
+        ];
+
+        let mut prices = BTreeMap::new();
+        insert_klines(&mut prices, klines, OhlcField::Close).unwrap();
+
+        assert_eq!(prices.len(), 2);
+        assert!(!prices.contains_key(&60_060_000));
+    }
+
+    #[test]
+    fn insert_klines_rejects_malformed_entry() {
+        let klines = vec![vec![json!(60_000_000), json!("1.0")]];
+
+        let mut prices = BTreeMap::new();
+        assert!(insert_klines(&mut prices, klines, OhlcField::Close).is_err());
+    }
+
+    #[test]
+    fn pagination_cursor_advances_past_last_seen_minute() {
+        // The open time returned for a page should make the next request's
+        // cursor start one minute after the last entry, not repeat it (the
+        // endpoint's `endTime` is inclusive).
+        let klines: Vec<_> = (0..3)
+            .map(|i| kline(60_000_000 + i * 60_000, "1", "1", "1", "1", "1"))
+            .collect();
+
+        let mut prices = BTreeMap::new();
+        let last_open_ms = insert_klines(&mut prices, klines, OhlcField::Close).unwrap();
+        let next_cursor = last_open_ms + 60_000;
+
+        assert_eq!(next_cursor, 60_180_000);
+        assert!(!prices.contains_key(&next_cursor));
+    }
+
+    #[test]
+    fn accumulate_vwap_from_klines_skips_zero_volume_minutes() {
+        let klines = vec![
+            kline(60_000_000, "1.0", "1.2", "0.9", "1.1", "10"),
+            kline(60_060_000, "1.1", "1.3", "1.0", "1.2", "0"),
+        ];
+
+        let mut weighted_sum = 0.0;
+        let mut volume_sum = 0.0;
+        accumulate_vwap_from_klines(&klines, &mut weighted_sum, &mut volume_sum).unwrap();
+
+        // Only the first, non-zero-volume minute should contribute.
+        let expected_typical_price = (1.2 + 0.9 + 1.1) / 3.0;
+        assert_eq!(volume_sum, 10.0);
+        assert_eq!(weighted_sum, expected_typical_price * 10.0);
+    }
+
+    #[test]
+    fn accumulate_vwap_from_klines_continues_across_pages() {
+        // Simulates two paginated requests for the same day: accumulators
+        // must be shared so the result reflects the whole day, not just the
+        // last page.
+        let page1 = vec![kline(60_000_000, "1.0", "1.0", "1.0", "1.0", "10")];
+        let page2 = vec![kline(60_060_000, "2.0", "2.0", "2.0", "2.0", "10")];
+
+        let mut weighted_sum = 0.0;
+        let mut volume_sum = 0.0;
+        accumulate_vwap_from_klines(&page1, &mut weighted_sum, &mut volume_sum).unwrap();
+        accumulate_vwap_from_klines(&page2, &mut weighted_sum, &mut volume_sum).unwrap();
+
+        assert_eq!(volume_sum, 20.0);
+        assert_eq!(weighted_sum / volume_sum, 1.5);
+    }
+
+    #[test]
+    fn to_coinbase_product_splits_known_quote_currencies() {
+        assert_eq!(to_coinbase_product("DOTEUR").unwrap(), "DOT-EUR");
+        assert_eq!(to_coinbase_product("DOTUSDT").unwrap(), "DOT-USDT");
+        assert_eq!(to_coinbase_product("DOTUSDC").unwrap(), "DOT-USDC");
+    }
+
+    #[test]
+    fn to_coinbase_product_rejects_unknown_quote_currency() {
+        assert!(to_coinbase_product("DOTXYZ").is_err());
+    }
+
+    #[test]
+    fn vwap_prices_in_range_by_day_calls_once_per_distinct_day() {
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 23, 58, 0).unwrap();
+        let end = start + Duration::minutes(4); // spans into 2023-01-02
+
+        let mut calls = Vec::new();
+        let prices = vwap_prices_in_range_by_day(start, end, |datetime| {
+            calls.push(datetime);
+            Ok(if datetime.date_naive() == start.date_naive() {
+                1.0
+            } else {
+                2.0
+            })
+        })
+        .unwrap();
+
+        // One call per distinct UTC day covered by the range, not one per minute.
+        assert_eq!(calls.len(), 2);
+        assert_eq!(prices.get(&minute_timestamp_ms(start)), Some(&1.0));
+        assert_eq!(prices.get(&minute_timestamp_ms(end)), Some(&2.0));
+    }
+}
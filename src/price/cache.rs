@@ -0,0 +1,167 @@
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+// `u64` minute timestamp (8 bytes) + `f64` price (8 bytes), both little-endian.
+const RECORD_LEN: usize = 16;
+
+/// On-disk append-only cache of fetched prices for a single symbol/basis
+/// combination, keyed by minute-truncated timestamp in milliseconds.
+///
+/// Mirrors the fixed-offset row encoding used in high-throughput trade
+/// pipelines: each record is a `u64` timestamp immediately followed by an
+/// `f64` price, with one file per symbol so no tag is needed inside the
+/// record itself. Entries are kept in memory and appended to disk in
+/// [`PriceCache::flush`], which also runs automatically on drop.
+pub struct PriceCache {
+    path: PathBuf,
+    entries: BTreeMap<i64, f64>,
+    pending: Vec<(i64, f64)>,
+}
+
+impl PriceCache {
+    /// Load the cache file `{dir}/{name}.cache`, creating `dir` if needed.
+    pub fn load(dir: &Path, name: &str) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("cannot create cache directory {}", dir.display()))?;
+
+        let path = dir.join(format!("{name}.cache"));
+        let mut entries = BTreeMap::new();
+
+        if path.exists() {
+            let mut bytes = Vec::new();
+            File::open(&path)
+                .with_context(|| format!("cannot open cache file {}", path.display()))?
+                .read_to_end(&mut bytes)?;
+
+            for chunk in bytes.chunks(RECORD_LEN) {
+                if chunk.len() != RECORD_LEN {
+                    // A truncated trailing record from an interrupted write; ignore it.
+                    break;
+                }
+                let timestamp =
+                    u64::from_le_bytes(chunk[0..8].try_into().expect("chunk is 16 bytes")) as i64;
+                let price = f64::from_le_bytes(chunk[8..16].try_into().expect("chunk is 16 bytes"));
+                entries.insert(timestamp, price);
+            }
+        }
+
+        Ok(Self {
+            path,
+            entries,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Look up a cached price for a minute-truncated timestamp in milliseconds.
+    pub fn get(&self, minute_timestamp_ms: i64) -> Option<f64> {
+        self.entries.get(&minute_timestamp_ms).copied()
+    }
+
+    /// Record a freshly fetched price, queued to be appended on the next
+    /// [`PriceCache::flush`].
+    pub fn insert(&mut self, minute_timestamp_ms: i64, price: f64) {
+        if self.entries.insert(minute_timestamp_ms, price).is_none() {
+            self.pending.push((minute_timestamp_ms, price));
+        }
+    }
+
+    /// Append newly inserted entries to disk.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("cannot open cache file {}", self.path.display()))?;
+
+        for (timestamp, price) in self.pending.drain(..) {
+            file.write_all(&(timestamp as u64).to_le_bytes())?;
+            file.write_all(&price.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for PriceCache {
+    fn drop(&mut self) {
+        // Best-effort: if the report already produced its output, a failure
+        // to persist the cache shouldn't turn into a panic on the way out.
+        let _ = self.flush();
+    }
+}
+
+/// Default platform cache directory for dotgain's price cache
+/// (e.g. `~/.cache/dotgain` on Linux).
+pub fn default_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("dotgain"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// A fresh, not-yet-created directory under the OS temp dir, unique to
+    /// this test process and `label`.
+    fn temp_cache_dir(label: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("dotgain-cache-test-{label}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn load_on_empty_dir_has_no_entries() {
+        let dir = temp_cache_dir("empty");
+
+        let cache = PriceCache::load(&dir, "DOTEUR").unwrap();
+        assert_eq!(cache.get(60_000_000), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn round_trips_entries_across_reload() {
+        let dir = temp_cache_dir("round-trip");
+
+        {
+            let mut cache = PriceCache::load(&dir, "DOTEUR").unwrap();
+            cache.insert(60_000_000, 1.23);
+            cache.insert(60_060_000, 1.24);
+            cache.flush().unwrap();
+        }
+
+        let reloaded = PriceCache::load(&dir, "DOTEUR").unwrap();
+        assert_eq!(reloaded.get(60_000_000), Some(1.23));
+        assert_eq!(reloaded.get(60_060_000), Some(1.24));
+        assert_eq!(reloaded.get(60_120_000), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ignores_truncated_trailing_record() {
+        let dir = temp_cache_dir("truncated");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("DOTEUR.cache");
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&60_000_000u64.to_le_bytes());
+        bytes.extend_from_slice(&1.5f64.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 10]); // incomplete trailing record
+        fs::write(&path, &bytes).unwrap();
+
+        let cache = PriceCache::load(&dir, "DOTEUR").unwrap();
+        assert_eq!(cache.get(60_000_000), Some(1.5));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Context, Result};
-use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono::{offset::LocalResult, DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 
 /// `TryFrom` alternative for conversions from human formats.
 pub trait TryFromHuman
@@ -11,26 +12,34 @@ where
     fn try_from_human(string: &str) -> Result<Self, Self::Error>;
 }
 
-impl TryFromHuman for DateTime<Utc> {
+impl TryFromHuman for NaiveDateTime {
     type Error = anyhow::Error;
 
-    fn try_from_human(string: &str) -> Result<DateTime<Utc>> {
+    fn try_from_human(string: &str) -> Result<NaiveDateTime> {
         if let Ok(naive_datetime) = NaiveDateTime::parse_from_str(string, "%Y-%m-%d %H:%M:%S") {
-            return Ok(Utc.from_utc_datetime(&naive_datetime));
+            return Ok(naive_datetime);
         }
         if let Ok(naive_datetime) = NaiveDateTime::parse_from_str(string, "%Y-%m-%d %H:%M") {
-            return Ok(Utc.from_utc_datetime(&naive_datetime));
+            return Ok(naive_datetime);
         }
         if let Ok(naive_date) = NaiveDate::parse_from_str(string, "%Y-%m-%d") {
-            let naive_datetime = naive_date
+            return Ok(naive_date
                 .and_hms_opt(0, 0, 0)
-                .expect("zero H, M, S are valid");
-            return Ok(Utc.from_utc_datetime(&naive_datetime));
+                .expect("zero H, M, S are valid"));
         }
         Err(anyhow!("invalid date: {string}"))
     }
 }
 
+impl TryFromHuman for DateTime<Utc> {
+    type Error = anyhow::Error;
+
+    fn try_from_human(string: &str) -> Result<DateTime<Utc>> {
+        let naive_datetime = NaiveDateTime::try_from_human(string)?;
+        Ok(Utc.from_utc_datetime(&naive_datetime))
+    }
+}
+
 impl TryFromHuman for NaiveDate {
     type Error = anyhow::Error;
 
@@ -49,3 +58,22 @@ impl IntoHuman for NaiveDateTime {
         self.format("%Y-%m-%d %H:%M:%S").to_string()
     }
 }
+
+/// Localize a naive wall-clock datetime in `tz` and convert it to UTC.
+///
+/// Tax income must be attributed to the date it falls on in the user's local
+/// timezone, so the naive datetime from the CSV is interpreted in `tz`
+/// rather than assumed to be UTC. Errors instead of guessing when the local
+/// time is ambiguous (DST fall-back) or doesn't exist (DST spring-forward).
+pub fn localize_to_utc(naive: NaiveDateTime, tz: Tz) -> Result<DateTime<Utc>> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(local) => Ok(local.with_timezone(&Utc)),
+        LocalResult::Ambiguous(earliest, latest) => Err(anyhow!(
+            "local time {naive} in {tz} is ambiguous due to a DST transition \
+             (could be {earliest} or {latest})"
+        )),
+        LocalResult::None => Err(anyhow!(
+            "local time {naive} in {tz} does not exist due to a DST transition"
+        )),
+    }
+}